@@ -1,8 +1,15 @@
 //! A layer/frame of which gets *stacked* to form the database
 pub mod mapper;
+pub mod compress;
+#[cfg(feature = "integrity")]
+pub mod integrity;
+pub(crate) mod io;
 
-use std::{borrow::Cow, io::{BufWriter, Read, Seek, Write}, ops::Range};
+use core::ops::Range;
+use alloc::borrow::Cow;
+use io::{BufWriter, Read, Seek, SeekFrom, Write};
 use crate::errors::Error;
+use compress::Codec;
 use mapper::Mapper;
 
 pub type Section<'l> = (Range<u64>, Cow<'l, [u8]>);
@@ -60,7 +67,7 @@ impl<'l,  Stream: Write + Read + Seek> Layer<'l, Stream> {
 
     #[inline]
     pub fn load(mut stream: Stream) -> Result<Self, Error> {
-        let mut buffer = [0u8; (u64::BITS as usize/8) * 3]; // buffer for three `u64` values: `size`, `bounds.start`, `bounds.end`
+        let mut buffer = [0u8; HEADER_LEN]; // `size`, `bounds.start`, `bounds.end`, `index_offset` and the codec tag
         match stream.read_exact(&mut buffer) {
             Ok(_) => (),
             Err(_) => return Err(Error::DBCorrupt(Box::new(Error::InvalidLayer))),
@@ -70,16 +77,53 @@ impl<'l,  Stream: Write + Read + Seek> Layer<'l, Stream> {
         // read metadata; return corruption error if failure
         let size = get_u64(&buffer, 0..8)?;
         let bounds = get_u64(&buffer, 8..16)?..get_u64(&buffer, 16..24)?;
+        let index_offset = get_u64(&buffer, 24..32)?;
+        Codec::from_tag(buffer[32])?; // validate the recorded codec; sections carry their own tag
+
+        // verify the header checksum before trusting any of the metadata above
+        #[cfg(feature = "integrity")]
+        {
+            let mut checksum = [0u8; integrity::CHECKSUM_LEN as usize];
+            match stream.read_exact(&mut checksum) {
+                Ok(_) => (),
+                Err(_) => return Err(Error::DBCorrupt(Box::new(Error::InvalidLayer))),
+            };
+            integrity::verify(&buffer, u32::from_be_bytes(checksum))?;
+        }
+
+        // the sections run from the header up to where the offset index begins;
+        // load that index (24 bytes per entry) into memory for O(log n) lookup
+        stream.seek(SeekFrom::Start(index_offset))?;
+        let mut index = mapper::Index::new();
+        let mut entry = [0u8; 24];
+        while stream.read_exact(&mut entry).is_ok() {
+            index.push((get_u64(&entry, 0..8)?, get_u64(&entry, 8..16)?, get_u64(&entry, 16..24)?));
+        }
 
         Ok(Self {
             bounds: Some(bounds),
-            mapper: Mapper::Disk,
+            mapper: Mapper::Disk { sections_end: index_offset, index },
             size,
             read_cursor: (0, 0),
             stream,
         })
     }
 
+    /// Resolves the position (a file offset, or a section index for heap layers)
+    /// to begin a read from: the cached [`read_cursor`] when `addr.start` is at
+    /// or past it — making ascending reads amortized O(1) — otherwise a binary
+    /// search of the offset index.
+    ///
+    /// [`read_cursor`]: Self::read_cursor
+    #[inline]
+    fn read_from(&self, addr: &Range<u64>) -> u64 {
+        if self.read_cursor != (0, 0) && addr.start >= self.read_cursor.0 {
+            self.read_cursor.1 as u64
+        } else {
+            self.mapper.seek(addr.start)
+        }
+    }
+
     /// Checks for collisions on the current layer
     #[inline]
     pub fn check_collisions(&mut self, range: &Range<u64>) -> Result<Box<[Range<u64>]>, Error> {
@@ -89,13 +133,18 @@ impl<'l,  Stream: Write + Read + Seek> Layer<'l, Stream> {
             None => return Ok(Box::new([])),
         }
         
+        let from = self.read_from(range);
         let mut err = Ok(());
-        let out = self.mapper.iter(&mut self.stream, self.size, REWIND_IDX)?
+        let mut cursor = self.read_cursor;
+        let out = self.mapper.iter(&mut self.stream, from)?
             .scan(&mut err, until_err) // handles the errors
-            .filter(|(r, _)| range.start < r.end && r.start < range.end)
-            .map(|(r, _)| range.start.max(r.start)..std::cmp::min(range.end, r.end))
+            .take_while(|((r, _), _)| r.start < range.end) // sorted by start; nothing past this can overlap
+            .inspect(|((r, _), off)| cursor = (r.start, *off as usize)) // remember where we got to
+            .filter(|((r, _), _)| range.start < r.end && r.start < range.end)
+            .map(|((r, _), _)| range.start.max(r.start)..core::cmp::min(range.end, r.end))
             .collect();
         err?;
+        self.read_cursor = cursor;
         Ok(out)
     }
 
@@ -121,15 +170,22 @@ impl<'l,  Stream: Write + Read + Seek> Layer<'l, Stream> {
     /// **warning:** will throw `out-of-bounds` error (or undefined behaviour) if the read is accross two sections *(each read can only be on one section of a layer)*
     #[inline]
     pub fn read_unchecked(&mut self, addr: &Range<u64>) -> Result<(Range<usize>, Cow<[u8]>), Error> {
+        let from = self.read_from(addr);
         let mut err = Ok(());
-        let out = self.mapper.iter(&mut self.stream, self.size, REWIND_IDX)? // todo: Actually use the read-cursor so that you don't have to iterate through everything to get to where you want
+        let out = self.mapper.iter(&mut self.stream, from)?
             .scan(&mut err, until_err) // handles errors
-            .find(|(r, _)| r.start <= addr.start && addr.end <= r.end) // read must be equal to or within layer section
-            .map(|(r, x)| ((addr.start-r.start) as usize..(addr.end-r.start) as usize, x));
+            .take_while(|((r, _), _)| r.start <= addr.start) // sorted by start; the owning section can only be at/before here
+            .find(|((r, _), _)| r.start <= addr.start && addr.end <= r.end) // read must be equal to or within layer section
+            .map(|((r, x), off)| ((addr.start-r.start) as usize..(addr.end-r.start) as usize, x, r.start, off));
         err?;
-        out
-            .map(Ok)
-            .unwrap_or(Err(Error::OutOfBounds))
+
+        match out {
+            Some((relative, data, start, off)) => {
+                self.read_cursor = (start, off as usize);
+                Ok((relative, data))
+            },
+            None => Err(Error::OutOfBounds),
+        }
     }
 
     /// Writes to the heap layer without checking for collisions
@@ -145,12 +201,9 @@ impl<'l,  Stream: Write + Read + Seek> Layer<'l, Stream> {
         let map_idx = if write_cursor.0 == idx {
             write_cursor.1
         } else {
-            mapper
-                .iter()
-                .enumerate()
-                .find(|(_, (r, _))| r.start > idx)
-                .map(|(i, _)| i)
-                .unwrap_or(0) // if map is empty write to the first index
+            // the map is sorted by `range.start`, so binary search for the first
+            // section that starts after `idx` instead of scanning linearly
+            mapper.partition_point(|(r, _)| r.start <= idx)
         };
 
         // insert data into the map and update write cursor & size
@@ -160,42 +213,128 @@ impl<'l,  Stream: Write + Read + Seek> Layer<'l, Stream> {
 
         // Update bounds
         self.bounds = Some(match self.bounds {
-            Some(ref x) => std::cmp::min(x.start, range.start)..std::cmp::max(x.end, range.end),
+            Some(ref x) => core::cmp::min(x.start, range.start)..core::cmp::max(x.end, range.end),
             None => range,
         });
 
         Ok(())
     }
 
-    /// Moves the layer from the **heap** to **disk**
+    /// Moves the layer from the **heap** to **disk**, storing sections verbatim
     pub fn flush(&mut self) -> Result<(), Error> {
+        self.flush_compressed(Codec::default())
+    }
+
+    /// Moves the layer from the **heap** to **disk**, block-compressing each
+    /// section's payload with `codec` so random access is preserved. The codec
+    /// is recorded in the layer header and, per section, as a one-byte tag; a
+    /// [`Codec::Passthrough`] flush stores each payload verbatim, still framed
+    /// by the same tag and lengths.
+    pub fn flush_compressed(&mut self, codec: Codec) -> Result<(), Error> {
         const BUFFER_SIZE: usize = 1024 * 1024 * 4; // 4MiB buffer size
-        
+        /// fixed per-section overhead: the range (16) + codec tag (1) + the
+        /// stored and original lengths (8 each)
+        const SECTION_HEAD: u64 = 16 + 1 + 8 + 8;
+
         // don't flush if it's an empty layer or in read-only mode
         let (bounds, mapper) = if let (Some(b), Mapper::Heap { mapper, .. }) = (&self.bounds, &self.mapper) { (b, mapper) } else {  return Ok(()) };
+
+        // compress every section up front, building the offset index as we go and
+        // learning where the sections end (i.e. where the index will begin)
+        let mut index = mapper::Index::with_capacity(mapper.len());
+        let mut sections = Vec::with_capacity(mapper.len());
+        let mut offset = REWIND_IDX;
+        for (range, data) in mapper {
+            let stored = codec.compress(data).into_owned();
+            index.push((range.start, range.end, offset));
+            offset += SECTION_HEAD + stored.len() as u64;
+            #[cfg(feature = "integrity")]
+            { offset += integrity::CHECKSUM_LEN; }
+            sections.push((range.clone(), data.len() as u64, stored));
+        }
+        let sections_end = offset;
+
         let mut file = BufWriter::with_capacity(BUFFER_SIZE, &mut self.stream);
 
         // write from the start
-        file.rewind()?;
+        file.seek(SeekFrom::Start(0))?;
 
-        // write the bounds & size of the layer
-        file.write_all(&self.size.to_be_bytes())?;
-        file.write_all(&bounds.start.to_be_bytes())?;
-        file.write_all(&bounds.end.to_be_bytes())?;
+        // write the size, bounds, index offset and codec of the layer
+        let header = {
+            let mut header = [0u8; HEADER_LEN];
+            header[0..8].copy_from_slice(&self.size.to_be_bytes());
+            header[8..16].copy_from_slice(&bounds.start.to_be_bytes());
+            header[16..24].copy_from_slice(&bounds.end.to_be_bytes());
+            header[24..32].copy_from_slice(&sections_end.to_be_bytes());
+            header[32] = codec.tag();
+            header
+        };
+        file.write_all(&header)?;
 
-        // we assume that the map is already sorted
-        for (range, data) in mapper {
-            file.write_all(&range.start.to_be_bytes())?;
-            file.write_all(&range.end.to_be_bytes())?;
-            file.write_all(data)?;
+        // checksum over the header so a truncated/flipped header is caught on load
+        #[cfg(feature = "integrity")]
+        file.write_all(&integrity::checksum(&header).to_be_bytes())?;
+
+        // we assume that the map is already sorted; each section is
+        // `range.start || range.end || codec || stored_len || orig_len || stored`
+        for (range, orig_len, stored) in &sections {
+            let mut record = Vec::with_capacity(SECTION_HEAD as usize + stored.len());
+            record.extend_from_slice(&range.start.to_be_bytes());
+            record.extend_from_slice(&range.end.to_be_bytes());
+            record.push(codec.tag());
+            record.extend_from_slice(&(stored.len() as u64).to_be_bytes());
+            record.extend_from_slice(&orig_len.to_be_bytes());
+            record.extend_from_slice(stored);
+            file.write_all(&record)?;
+
+            // per-section checksum over the whole section record
+            #[cfg(feature = "integrity")]
+            file.write_all(&integrity::checksum(&record).to_be_bytes())?;
+        }
+
+        // append the offset index (24 bytes per entry) at the tail of the file
+        for (start, end, off) in &index {
+            file.write_all(&start.to_be_bytes())?;
+            file.write_all(&end.to_be_bytes())?;
+            file.write_all(&off.to_be_bytes())?;
         }
 
         // flush file and switch to disk layer
         file.flush()?;
-        self.mapper = Mapper::Disk;
-        
+        self.mapper = Mapper::Disk { sections_end, index };
+        // the cursor's second field means a section index on the heap but a
+        // file offset on disk — reset it so a stale heap index isn't reused as
+        // a bogus seek position by the next disk read
+        self.read_cursor = (0, 0);
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'l> Layer<'l, std::fs::File> {
+    /// Upgrades a flushed (disk) layer to a zero-copy memory-mapped layer.
+    ///
+    /// After this, `read_unchecked` hands back `Cow::Borrowed` slices pointing
+    /// straight into the mapping for sections stored with
+    /// [`Codec::Passthrough`] instead of copying each section out of the
+    /// `Stream`; compressed sections are still decompressed into an owned
+    /// buffer. A plain buffered [`Mapper::Disk`] stays the fallback for
+    /// streams that aren't file-backed.
+    #[inline]
+    pub fn mmap(&mut self) -> Result<(), Error> {
+        self.mapper = core::mem::take(&mut self.mapper).into_mmap(&self.stream)?;
         Ok(())
     }
 }
 
-pub const REWIND_IDX: u64 = 8 + 8 + 8; // skip the `u64`s: `layer_size`, `layer_bound.start` and `layer_bound.end`
+/// The fixed header: the `u64`s `size`, `bounds.start`, `bounds.end` and
+/// `index_offset`, followed by the one-byte codec tag
+pub const HEADER_LEN: usize = 8 + 8 + 8 + 8 + 1;
+
+// skip the header to reach the first section
+#[cfg(not(feature = "integrity"))]
+pub const REWIND_IDX: u64 = HEADER_LEN as u64;
+// ...plus the trailing header checksum when integrity checking is enabled
+#[cfg(feature = "integrity")]
+pub const REWIND_IDX: u64 = HEADER_LEN as u64 + integrity::CHECKSUM_LEN;
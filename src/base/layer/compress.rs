@@ -0,0 +1,60 @@
+//! Swappable block compression of layer sections
+//!
+//! Each section's payload is compressed independently so random access is
+//! preserved: `read_unchecked` only has to decompress the single section it
+//! lands on. The codec a layer was flushed with is recorded in its header and,
+//! per section, as a one-byte tag ahead of the stored payload — the default
+//! [`Codec::Passthrough`] stores bytes verbatim so an uncompressed layer pays
+//! nothing.
+use alloc::borrow::Cow;
+use crate::errors::Error;
+
+/// The codec a section's payload is stored with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Codec {
+    /// Stored verbatim; the default passthrough
+    #[default]
+    Passthrough,
+    /// LZ4 block compression
+    Lz4,
+}
+
+impl Codec {
+    /// The one-byte on-disk tag for the codec
+    #[inline]
+    pub const fn tag(self) -> u8 {
+        match self {
+            Self::Passthrough => 0,
+            Self::Lz4 => 1,
+        }
+    }
+
+    /// Reconstructs a codec from its on-disk tag, erroring on an unknown tag
+    #[inline]
+    pub fn from_tag(tag: u8) -> Result<Self, Error> {
+        match tag {
+            0 => Ok(Self::Passthrough),
+            1 => Ok(Self::Lz4),
+            _ => Err(Error::DBCorrupt(Box::new(Error::InvalidLayer))),
+        }
+    }
+
+    /// Compresses a section payload for storage
+    #[inline]
+    pub fn compress(self, data: &[u8]) -> Cow<[u8]> {
+        match self {
+            Self::Passthrough => Cow::Borrowed(data),
+            Self::Lz4 => Cow::Owned(lz4_flex::block::compress(data)),
+        }
+    }
+
+    /// Decompresses a stored payload back to its original `len` bytes
+    #[inline]
+    pub fn decompress(self, stored: &[u8], len: usize) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Passthrough => Ok(stored.to_vec()),
+            Self::Lz4 => lz4_flex::block::decompress(stored, len)
+                .map_err(|_| Error::DBCorrupt(Box::new(Error::InvalidLayer))),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Swappable integrity checking for layer headers and sections
+//!
+//! A layer stores a 32-bit checksum over its [`HEADER_LEN`](super::HEADER_LEN)-byte
+//! header and, per section, a trailing checksum over the whole section record
+//! (`range.start || range.end || codec || stored_len || orig_len || stored`).
+//! The header is verified on [`load`](super::Layer::load) and a section each
+//! time it is visited while reading, turning a silently flipped byte into an
+//! [`Error::DBCorrupt`]. The whole thing compiles out behind the `integrity`
+//! feature for hot paths that would rather not pay for it.
+use crate::errors::Error;
+
+/// The on-disk width of a checksum in bytes
+pub const CHECKSUM_LEN: u64 = 4;
+
+/// A swappable checksum algorithm over a byte run
+pub trait Algorithm {
+    /// Computes the checksum of `bytes`
+    fn checksum(bytes: &[u8]) -> u32;
+}
+
+/// The default algorithm: CRC32C (Castagnoli), as used by thin-provisioning's
+/// checksum layer. Swap the [`Checksum`] alias to change it crate-wide.
+pub struct Crc32c;
+impl Algorithm for Crc32c {
+    #[inline]
+    fn checksum(bytes: &[u8]) -> u32 {
+        crc32c::crc32c(bytes)
+    }
+}
+
+/// The algorithm the database is built with
+pub type Checksum = Crc32c;
+
+/// Computes the checksum of `bytes` with the active [`Algorithm`]
+#[inline]
+pub fn checksum(bytes: &[u8]) -> u32 {
+    <Checksum as Algorithm>::checksum(bytes)
+}
+
+/// Verifies that `bytes` hash to `stored`, returning [`Error::DBCorrupt`] otherwise
+#[inline]
+pub fn verify(bytes: &[u8], stored: u32) -> Result<(), Error> {
+    if checksum(bytes) == stored {
+        Ok(())
+    } else {
+        Err(Error::DBCorrupt(Box::new(Error::InvalidLayer)))
+    }
+}
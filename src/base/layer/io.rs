@@ -0,0 +1,113 @@
+//! Internal alias over `std::io` / `core_io::io`
+//!
+//! With the default `std` feature the layer's I/O traits come straight from
+//! `std::io`; with it disabled they come from the `core_io` crate instead, plus
+//! small buffered shims standing in for `std::io::BufWriter` and
+//! `std::io::BufReader` (which `core_io` does not provide). Keeping the switch
+//! behind this one module means `Layer` and `Mapper` only ever name
+//! `io::{Read, Write, Seek, SeekFrom, BufWriter, BufReader}`.
+#[cfg(feature = "std")]
+pub(crate) use std::io::{BufReader, BufWriter, Read, Result, Seek, SeekFrom, Write};
+
+#[cfg(not(feature = "std"))]
+pub(crate) use core_io::io::{Read, Result, Seek, SeekFrom, Write};
+
+#[cfg(not(feature = "std"))]
+pub(crate) use shim::{BufReader, BufWriter};
+
+#[cfg(not(feature = "std"))]
+mod shim {
+    use super::{Read, Result, Seek, SeekFrom, Write};
+    extern crate alloc;
+    use alloc::{vec, vec::Vec};
+
+    /// A minimal `no_std` stand-in for `std::io::BufWriter` covering the subset
+    /// the flush path needs: capacity-bounded buffering plus a seekable sink.
+    pub struct BufWriter<W: Write + Seek> {
+        inner: W,
+        buf: Vec<u8>,
+        capacity: usize,
+    }
+
+    impl<W: Write + Seek> BufWriter<W> {
+        #[inline]
+        pub fn with_capacity(capacity: usize, inner: W) -> Self {
+            Self { inner, buf: Vec::with_capacity(capacity), capacity }
+        }
+
+        /// Writes any buffered bytes through to the inner sink
+        #[inline]
+        fn spill(&mut self) -> Result<()> {
+            if !self.buf.is_empty() {
+                self.inner.write_all(&self.buf)?;
+                self.buf.clear();
+            } Ok(())
+        }
+    }
+
+    impl<W: Write + Seek> Write for BufWriter<W> {
+        #[inline]
+        fn write(&mut self, data: &[u8]) -> Result<usize> {
+            if self.buf.len() + data.len() > self.capacity {
+                self.spill()?;
+            }
+            // bypass the buffer for writes that would not fit in it anyway
+            if data.len() >= self.capacity {
+                self.inner.write(data)
+            } else {
+                self.buf.extend_from_slice(data);
+                Ok(data.len())
+            }
+        }
+
+        #[inline]
+        fn flush(&mut self) -> Result<()> {
+            self.spill()?;
+            self.inner.flush()
+        }
+    }
+
+    impl<W: Write + Seek> Seek for BufWriter<W> {
+        #[inline]
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+            self.spill()?;
+            self.inner.seek(pos)
+        }
+    }
+
+    /// A minimal `no_std` stand-in for `std::io::BufReader`, refilling a fixed
+    /// buffer from the inner reader so section metadata and payloads are read in
+    /// large blocks rather than many small `read_exact` calls.
+    pub struct BufReader<R: Read> {
+        inner: R,
+        buf: Vec<u8>,
+        pos: usize,
+        cap: usize,
+    }
+
+    impl<R: Read> BufReader<R> {
+        #[inline]
+        pub fn with_capacity(capacity: usize, inner: R) -> Self {
+            Self { inner, buf: vec![0; capacity], pos: 0, cap: 0 }
+        }
+
+        #[inline]
+        fn fill(&mut self) -> Result<&[u8]> {
+            if self.pos >= self.cap {
+                self.cap = self.inner.read(&mut self.buf)?;
+                self.pos = 0;
+            }
+            Ok(&self.buf[self.pos..self.cap])
+        }
+    }
+
+    impl<R: Read> Read for BufReader<R> {
+        fn read(&mut self, out: &mut [u8]) -> Result<usize> {
+            let available = self.fill()?;
+            let n = available.len().min(out.len());
+            out[..n].copy_from_slice(&available[..n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! Maps a layer's sections to either the heap or the disk
+use alloc::borrow::Cow;
+use super::io::{BufReader, Read, Seek, SeekFrom};
+use super::compress::Codec;
+use crate::errors::Error;
+use super::{get_u64, Section, REWIND_IDX};
+
+/// fixed per-section overhead on disk: the range (16) + codec tag (1) + the
+/// stored and original payload lengths (8 each)
+const SECTION_HEAD: usize = 16 + 1 + 8 + 8;
+
+/// A compact sorted index of `(range.start, range.end, file_offset)` entries,
+/// kept in ascending `range.start` order so a [binary search] can jump straight
+/// to the first candidate section rather than rescanning the whole layer.
+///
+/// [binary search]: slice::partition_point
+pub type Index = Vec<(u64, u64, u64)>;
+
+/// Binary-searches `index` for the first entry that could overlap a query
+/// starting at `key`, returning its position in `index`.
+#[inline]
+fn lower_bound(index: &Index, key: u64) -> usize {
+    // the owning/overlapping section is the last one whose `start <= key`, or
+    // (when none precede `key`) the first section of all
+    index.partition_point(|(start, ..)| *start <= key).saturating_sub(1)
+}
+
+/// Maps a layer's sections to their backing store
+#[derive(Debug)]
+pub enum Mapper<'l> {
+    /// The sections live in memory, kept sorted by `range.start`
+    Heap {
+        /// The sorted in-memory sections
+        mapper: Vec<Section<'l>>,
+        /// The current write cursor to speed up sequential writes
+        write_cursor: (u64, usize),
+    },
+    /// The sections are streamed from the backing file on demand
+    Disk {
+        /// The file offset one past the last section (where iteration stops);
+        /// also where the appended offset index begins
+        sections_end: u64,
+        /// The sorted offset index loaded from the tail of the file
+        index: Index,
+    },
+    /// The backing file is memory-mapped so section payloads are served as
+    /// borrowed slices straight out of the mapping (zero-copy reads).
+    ///
+    /// A flushed disk layer is immutable — [`get_writer`](Self::get_writer)
+    /// rejects it — so the whole file is mapped once on [`into_mmap`] and never
+    /// grows; there is no in-place remapping to keep borrows valid.
+    ///
+    /// [`into_mmap`]: Self::into_mmap
+    #[cfg(feature = "std")]
+    Mmap {
+        /// The live mapping over the backing file
+        map: memmap2::Mmap,
+        /// The file offset one past the last section (where iteration stops);
+        /// also where the appended offset index begins
+        sections_end: u64,
+        /// The sorted offset index loaded from the tail of the file
+        index: Index,
+    },
+}
+
+impl Default for Mapper<'_> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'l> Mapper<'l> {
+    /// A fresh, empty heap mapper
+    #[inline]
+    pub fn new() -> Self {
+        Self::Heap { mapper: Vec::new(), write_cursor: (0, 0) }
+    }
+
+    /// Grabs the mutable heap map and its write cursor; errors on a read-only (disk) layer
+    #[inline]
+    pub fn get_writer(&mut self) -> Result<(&mut Vec<Section<'l>>, &mut (u64, usize)), Error> {
+        match self {
+            Self::Heap { mapper, write_cursor } => Ok((mapper, write_cursor)),
+            Self::Disk { .. } => Err(Error::ReadOnly),
+            #[cfg(feature = "std")]
+            Self::Mmap { .. } => Err(Error::ReadOnly),
+        }
+    }
+
+    /// Upgrades a disk mapper to a memory-mapped one, carrying its offset index
+    /// across; a no-op for heap or already-mapped layers. The layer is immutable
+    /// once flushed, so the whole file is mapped once and never remapped.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn into_mmap(self, file: &std::fs::File) -> Result<Self, Error> {
+        let (sections_end, index) = match self {
+            Self::Disk { sections_end, index } => (sections_end, index),
+            other => return Ok(other),
+        };
+        // SAFETY: the file outlives the mapping and the layer is read-only
+        let map = unsafe { memmap2::Mmap::map(file)? };
+        Ok(Self::Mmap { map, sections_end, index })
+    }
+
+    /// Binary-searches the layer's offset index for the position to begin
+    /// iterating at for a query starting at `key` — a file offset for disk/mmap
+    /// layers, or a section index for heap layers.
+    #[inline]
+    pub fn seek(&self, key: u64) -> u64 {
+        match self {
+            Self::Heap { mapper, .. } => mapper.partition_point(|(r, _)| r.start <= key).saturating_sub(1) as u64,
+            Self::Disk { index, .. } =>
+                index.get(lower_bound(index, key)).map(|(.., off)| *off).unwrap_or(REWIND_IDX),
+            #[cfg(feature = "std")]
+            Self::Mmap { index, .. } =>
+                index.get(lower_bound(index, key)).map(|(.., off)| *off).unwrap_or(REWIND_IDX),
+        }
+    }
+
+    /// Iterates over the layer's sections starting from `from` — a file offset
+    /// for disk/mmap layers (`REWIND_IDX` for a full walk) or a section index
+    /// for heap layers (`0` for a full walk), such as a cached [`read_cursor`]
+    /// position to skip the prefix — streaming through a buffered reader for
+    /// disk layers and borrowing straight out of the mapping for mmap layers.
+    ///
+    /// [`read_cursor`]: super::Layer::read_cursor
+    #[inline]
+    pub fn iter<'a, Stream: Read + Seek>(&'a self, stream: &'a mut Stream, from: u64) -> Result<SectionIter<'a, Stream>, Error> {
+        /// the buffered reader capacity for disk section reads
+        const BUFFER_SIZE: usize = 1024 * 64;
+
+        Ok(match self {
+            Self::Heap { mapper, .. } => SectionIter::Heap { slice: mapper, pos: from as usize },
+            Self::Disk { sections_end, .. } => {
+                stream.seek(SeekFrom::Start(from))?;
+                SectionIter::Disk { stream: BufReader::with_capacity(BUFFER_SIZE, stream), offset: from, end: *sections_end }
+            },
+            #[cfg(feature = "std")]
+            Self::Mmap { map, sections_end, .. } => SectionIter::Mmap { bytes: map, pos: from as usize, end: *sections_end as usize },
+        })
+    }
+}
+
+/// One visited section together with the file offset it starts at, used to
+/// advance the layer's [`read_cursor`](super::Layer::read_cursor)
+pub type Visited<'l> = (Section<'l>, u64);
+
+/// Lazily walks a layer's sections from whichever store backs the [`Mapper`]
+pub enum SectionIter<'a, Stream: Read + Seek> {
+    /// Borrowed straight from the in-memory map
+    Heap {
+        /// The sorted in-memory sections
+        slice: &'a [Section<'a>],
+        /// The index of the next section to yield
+        pos: usize,
+    },
+    /// Read section-by-section through a buffered view of the backing stream
+    Disk {
+        /// The buffered stream, already seeked to `offset`
+        stream: BufReader<&'a mut Stream>,
+        /// The file offset of the next section
+        offset: u64,
+        /// The file offset one past the last section
+        end: u64,
+    },
+    /// Borrowed straight out of the memory mapping
+    #[cfg(feature = "std")]
+    Mmap {
+        /// The mapped file bytes
+        bytes: &'a [u8],
+        /// The byte offset of the next section
+        pos: usize,
+        /// The byte offset one past the last section
+        end: usize,
+    },
+}
+
+impl<'a, Stream: Read + Seek> Iterator for SectionIter<'a, Stream> {
+    type Item = Result<Visited<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Heap { slice, pos } => slice.get(*pos).map(|(r, d)| {
+                let at = *pos as u64;
+                *pos += 1;
+                Ok(((r.clone(), Cow::Borrowed(d.as_ref())), at))
+            }),
+            Self::Disk { stream, offset, end } => {
+                if *offset >= *end { return None }
+                let sec_start = *offset;
+
+                // read the fixed section head, then the stored (possibly
+                // compressed) payload it describes, into one contiguous record
+                let mut record = vec![0u8; SECTION_HEAD];
+                if stream.read_exact(&mut record).is_err() {
+                    return Some(Err(Error::DBCorrupt(Box::new(Error::InvalidLayer))));
+                }
+                let start = match get_u64(&record, 0..8) { Ok(x) => x, Err(e) => return Some(Err(e)) };
+                let end_addr = match get_u64(&record, 8..16) { Ok(x) => x, Err(e) => return Some(Err(e)) };
+                let tag = record[16];
+                let stored_len = match get_u64(&record, 17..25) { Ok(x) => x as usize, Err(e) => return Some(Err(e)) };
+                let orig_len = match get_u64(&record, 25..33) { Ok(x) => x as usize, Err(e) => return Some(Err(e)) };
+
+                record.resize(SECTION_HEAD + stored_len, 0);
+                if stream.read_exact(&mut record[SECTION_HEAD..]).is_err() {
+                    return Some(Err(Error::DBCorrupt(Box::new(Error::InvalidLayer))));
+                }
+                *offset += record.len() as u64;
+
+                // verify the trailing checksum over the whole section record
+                #[cfg(feature = "integrity")]
+                {
+                    let mut checksum = [0u8; super::integrity::CHECKSUM_LEN as usize];
+                    if stream.read_exact(&mut checksum).is_err() {
+                        return Some(Err(Error::DBCorrupt(Box::new(Error::InvalidLayer))));
+                    }
+                    *offset += super::integrity::CHECKSUM_LEN;
+                    if let Err(e) = super::integrity::verify(&record, u32::from_be_bytes(checksum)) {
+                        return Some(Err(e));
+                    }
+                }
+
+                let codec = match Codec::from_tag(tag) { Ok(c) => c, Err(e) => return Some(Err(e)) };
+                let data = match codec.decompress(&record[SECTION_HEAD..], orig_len) { Ok(d) => d, Err(e) => return Some(Err(e)) };
+                Some(Ok(((start..end_addr, Cow::Owned(data)), sec_start)))
+            },
+            #[cfg(feature = "std")]
+            Self::Mmap { bytes, pos, end } => {
+                if *pos >= *end { return None }
+                let sec_start = *pos as u64;
+
+                let start = match get_u64(bytes, *pos..*pos + 8) { Ok(x) => x, Err(e) => return Some(Err(e)) };
+                let end_addr = match get_u64(bytes, *pos + 8..*pos + 16) { Ok(x) => x, Err(e) => return Some(Err(e)) };
+                let tag = match bytes.get(*pos + 16) { Some(t) => *t, None => return Some(Err(Error::DBCorrupt(Box::new(Error::InvalidLayer)))) };
+                let stored_len = match get_u64(bytes, *pos + 17..*pos + 25) { Ok(x) => x as usize, Err(e) => return Some(Err(e)) };
+                let orig_len = match get_u64(bytes, *pos + 25..*pos + 33) { Ok(x) => x as usize, Err(e) => return Some(Err(e)) };
+
+                let record_end = *pos + SECTION_HEAD + stored_len;
+                let record = match bytes.get(*pos..record_end) {
+                    Some(x) => x,
+                    None => return Some(Err(Error::DBCorrupt(Box::new(Error::InvalidLayer)))),
+                };
+                let stored = &record[SECTION_HEAD..];
+                *pos = record_end;
+
+                // verify the trailing checksum over the whole section record
+                #[cfg(feature = "integrity")]
+                {
+                    let clen = super::integrity::CHECKSUM_LEN as usize;
+                    let checksum = match bytes.get(*pos..*pos + clen).and_then(|x| x.try_into().ok()) {
+                        Some(x) => u32::from_be_bytes(x),
+                        None => return Some(Err(Error::DBCorrupt(Box::new(Error::InvalidLayer)))),
+                    };
+                    *pos += clen;
+                    if let Err(e) = super::integrity::verify(record, checksum) {
+                        return Some(Err(e));
+                    }
+                }
+
+                let codec = match Codec::from_tag(tag) { Ok(c) => c, Err(e) => return Some(Err(e)) };
+                // an uncompressed section is served straight out of the mapping;
+                // a compressed one must be decompressed into an owned buffer
+                let data = match codec {
+                    Codec::Passthrough => Cow::Borrowed(stored),
+                    _ => match codec.decompress(stored, orig_len) { Ok(d) => Cow::Owned(d), Err(e) => return Some(Err(e)) },
+                };
+                Some(Ok(((start..end_addr, data), sec_start)))
+            },
+        }
+    }
+}